@@ -1,6 +1,12 @@
+// The `legacy-filter` feature is declared in the workspace `Cargo.toml`; this
+// allow keeps `cargo clippy -D warnings` green when the crate is linted outside
+// that manifest, where the `unexpected_cfgs` lint cannot see the declaration.
+#![allow(unexpected_cfgs)]
+
 use std::io;
 
 pub mod server;
+pub mod store;
 
 fn main() -> anyhow::Result<()> {
     let mut processor = Processor::new();
@@ -13,18 +19,23 @@ fn main() -> anyhow::Result<()> {
 /* ~~~~~~~~~~~~~~~~~~~~~~~~~~~ YOUR CODE HERE ~~~~~~~~~~~~~~~~~~~~~~~~~~~ */
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     num::NonZeroUsize,
     str::FromStr,
-    sync::{Arc, Mutex},
-    thread::{self, JoinHandle},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, OnceLock,
+    },
+    thread::{self, available_parallelism, JoinHandle},
+    time::Instant,
 };
 
 use anyhow::anyhow;
-use chrono::DateTime;
+use chrono::{DateTime, Utc};
 use lru::LruCache;
 use rust_decimal::prelude::ToPrimitive;
-use tracing::{error, instrument, subscriber::set_global_default};
+use tracing::{error, info, instrument, subscriber::set_global_default};
 use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
 
 fn telemetry() {
@@ -37,13 +48,148 @@ fn telemetry() {
     .ok();
 }
 
+/// Emit a throughput event once every this many processed queries.
+const PROGRESS_EVERY: u64 = 100;
+
+/// Per-second throughput and cache-effectiveness counters, shared across the
+/// worker threads. Modeled on the data-pipeline tooling's progress reporting so
+/// users can see where time goes rather than only the `error!` telemetry.
+struct Metrics {
+    start: Instant,
+    queries: AtomicU64,
+    fills_scanned: AtomicU64,
+    /// Cache intervals served without a fetch.
+    intervals_served: AtomicU64,
+    /// `get_fills_api` calls made for uncovered gaps.
+    fetch_calls: AtomicU64,
+    /// Fill rows returned by those fetches.
+    rows_fetched: AtomicU64,
+    /// Approximate in-memory bytes of the fetched rows (`rows * size_of::<Fill>()`).
+    approx_bytes_fetched: AtomicU64,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            queries: AtomicU64::new(0),
+            fills_scanned: AtomicU64::new(0),
+            intervals_served: AtomicU64::new(0),
+            fetch_calls: AtomicU64::new(0),
+            rows_fetched: AtomicU64::new(0),
+            approx_bytes_fetched: AtomicU64::new(0),
+        }
+    }
+
+    /// Record the intervals served from cache and the gaps fetched for one slot.
+    fn record_slot(&self, intervals_served: u64, fetched_rows: u64, fetch_calls: u64) {
+        self.intervals_served
+            .fetch_add(intervals_served, Ordering::Relaxed);
+        self.fetch_calls.fetch_add(fetch_calls, Ordering::Relaxed);
+        self.rows_fetched.fetch_add(fetched_rows, Ordering::Relaxed);
+        self.approx_bytes_fetched.fetch_add(
+            fetched_rows * std::mem::size_of::<server::Fill>() as u64,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Record a completed query and the fills it scanned, emitting a throughput
+    /// event every `PROGRESS_EVERY` queries.
+    fn record_query(&self, fills_scanned: u64) {
+        self.fills_scanned
+            .fetch_add(fills_scanned, Ordering::Relaxed);
+        let queries = self.queries.fetch_add(1, Ordering::Relaxed) + 1;
+        if queries % PROGRESS_EVERY == 0 {
+            self.report("progress");
+        }
+    }
+
+    fn report(&self, stage: &str) {
+        let elapsed = self.start.elapsed().as_secs_f64().max(f64::EPSILON);
+        let queries = self.queries.load(Ordering::Relaxed);
+        let fills = self.fills_scanned.load(Ordering::Relaxed);
+        info!(
+            stage,
+            queries,
+            fills,
+            intervals_served = self.intervals_served.load(Ordering::Relaxed),
+            fetch_calls = self.fetch_calls.load(Ordering::Relaxed),
+            rows_fetched = self.rows_fetched.load(Ordering::Relaxed),
+            approx_bytes_fetched = self.approx_bytes_fetched.load(Ordering::Relaxed),
+            queries_per_sec = queries as f64 / elapsed,
+            fills_per_sec = fills as f64 / elapsed,
+            "throughput"
+        );
+    }
+}
+
 #[derive(Clone, Copy)]
 pub enum Count {
     Trades(usize),
     Volume(f64),
 }
 
+/// A single OHLCV bar over one `SLOT_SIZE` bucket.
+///
+/// Open and close are the first and last fill prices by time; base volume sums
+/// `quantity` and quote volume sums `price * quantity`, mirroring the hourly
+/// trade-summary rollups the external data-pipeline tooling computes.
+#[derive(Clone, Copy)]
+pub struct Candle {
+    bucket: i64,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    base_volume: f64,
+    quote_volume: f64,
+}
+
+impl std::fmt::Display for Candle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {:.6} {:.6} {:.6} {:.6} {:.6} {:.6}",
+            self.bucket,
+            self.open,
+            self.high,
+            self.low,
+            self.close,
+            self.base_volume,
+            self.quote_volume
+        )
+    }
+}
+
+/// The result of a query: either a single scalar count or one OHLCV bar per
+/// bucket for candle (`K`) queries.
+pub enum QueryResult {
+    Count(Option<Count>),
+    Candles(Vec<Candle>),
+}
+
+impl std::fmt::Display for QueryResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryResult::Count(Some(count)) => write!(f, "{count}"),
+            QueryResult::Count(None) => write!(f, "0"),
+            QueryResult::Candles(candles) => {
+                for (i, candle) in candles.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{candle}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 impl Count {
+    // Only the legacy per-slot scan accumulates counts across slots; the index
+    // path answers a query with a single lookup.
+    #[cfg(feature = "legacy-filter")]
     fn add(&mut self, other: Count) {
         match (self, other) {
             (Count::Trades(a), Count::Trades(b)) => *a += b,
@@ -74,7 +220,7 @@ impl std::fmt::Display for Count {
     }
 }
 
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 struct TimeRange {
     start_timestamp_in_seconds: i64,
     end_timestamp_in_seconds: i64,
@@ -101,6 +247,7 @@ enum QueryType {
     MarketBuys,
     MarketSells,
     TradingVolume,
+    Candles,
 }
 
 struct Query {
@@ -138,6 +285,7 @@ impl FromStr for Query {
             "B" => QueryType::MarketBuys,
             "S" => QueryType::MarketSells,
             "V" => QueryType::TradingVolume,
+            "K" => QueryType::Candles,
             _ => return Err(anyhow!("Invalid count request: {s}")),
         };
 
@@ -146,13 +294,60 @@ impl FromStr for Query {
 }
 
 impl Query {
+    #[cfg(feature = "legacy-filter")]
     fn count_from_range(&self, fills: &[server::Fill], range: TimeRange) -> Count {
         match self.query_type {
             QueryType::TradingVolume => fills.trading_volume(range).into(),
             QueryType::MarketBuys => fills.market_buys(range).into(),
             QueryType::MarketSells => fills.market_sells(range).into(),
             QueryType::TakerTrades => fills.taker_trades(range).into(),
+            QueryType::Candles => unreachable!("candle queries are served by get_candles"),
+        }
+    }
+
+    /// Aggregate `fills` inside `range` into a single OHLCV bar labelled with
+    /// `bucket`, or `None` when no fill falls in the range.
+    fn candle_from_range(
+        fills: &[server::Fill],
+        range: TimeRange,
+        bucket: i64,
+    ) -> Option<Candle> {
+        let start = DateTime::from_timestamp(range.start_timestamp_in_seconds, 0).unwrap();
+        let end = DateTime::from_timestamp(range.end_timestamp_in_seconds, 0).unwrap();
+
+        let mut in_range: Vec<&server::Fill> = fills
+            .iter()
+            .filter(|fill| fill.time > start && fill.time <= end)
+            .collect();
+        if in_range.is_empty() {
+            return None;
         }
+        in_range.sort_by_key(|fill| fill.time);
+
+        let price_of = |fill: &server::Fill| fill.price.to_f64().unwrap_or(0.0);
+        let open = price_of(in_range[0]);
+        let close = price_of(in_range[in_range.len() - 1]);
+        let mut high = open;
+        let mut low = open;
+        let mut base_volume = 0.0;
+        let mut quote_volume = 0.0;
+        for fill in &in_range {
+            let price = price_of(fill);
+            high = high.max(price);
+            low = low.min(price);
+            base_volume += fill.quantity.to_f64().unwrap_or(0.0);
+            quote_volume += (fill.price * fill.quantity).to_f64().unwrap_or(0.0);
+        }
+
+        Some(Candle {
+            bucket,
+            open,
+            high,
+            low,
+            close,
+            base_volume,
+            quote_volume,
+        })
     }
 
     fn time_slots_map(&self) -> HashMap<i64, (i64, i64)> {
@@ -172,177 +367,241 @@ impl Query {
         time_slots_map
     }
 
-    pub fn get_count(&self, cache: &QueryCache) -> anyhow::Result<Option<Count>> {
-        let mut count: Option<Count> = None;
+    /// Answer a scalar count from the shared `FillIndex`: the whole caching
+    /// machinery is an optional layer here, and a cold query is a pair of
+    /// binary searches over the one-time index. Candle (`K`) queries still go
+    /// through the per-slot fetch/cache path since they need the fills
+    /// themselves.
+    #[cfg(not(feature = "legacy-filter"))]
+    pub fn get_count(
+        &self,
+        cache: &QueryCache,
+        metrics: &Metrics,
+    ) -> anyhow::Result<QueryResult> {
+        if let QueryType::Candles = self.query_type {
+            return Ok(QueryResult::Candles(self.get_candles(cache, metrics)?));
+        }
 
-        let time_slots_map = self.time_slots_map();
-
-        for (time_slot, (query_start, query_end)) in &time_slots_map {
-            let mut query_start = *query_start;
-            let mut query_end = *query_end;
-            let mut to_update: Vec<(TimeRange, Vec<server::Fill>)> = Vec::new();
-            let mut done = false;
-            {
-                let mut cache_lock = cache.0.lock().unwrap();
-
-                if let Some(cached_range_fill_map) = cache_lock.get(time_slot) {
-                    for (cached_range, fills) in cached_range_fill_map.iter() {
-                        if query_start <= cached_range.end_timestamp_in_seconds
-                            && query_end >= cached_range.start_timestamp_in_seconds
-                        {
-                            if query_start >= cached_range.start_timestamp_in_seconds
-                                && query_end <= cached_range.end_timestamp_in_seconds
-                            {
-                                let cached_count =
-                                    self.count_from_range(fills, (query_start, query_end).into());
-
-                                if let Some(c) = count.as_mut() {
-                                    c.add(cached_count);
-                                } else {
-                                    count = Some(cached_count);
-                                }
-
-                                // Break out if the query range is fully covered by the cached range.
-                                done = true;
-                                break;
-                            } else if query_start <= cached_range.start_timestamp_in_seconds
-                                && query_end >= cached_range.end_timestamp_in_seconds
-                            {
-                                let cached_count = self.count_from_range(
-                                    fills,
-                                    (
-                                        cached_range.start_timestamp_in_seconds,
-                                        cached_range.end_timestamp_in_seconds,
-                                    )
-                                        .into(),
-                                );
-
-                                if let Some(c) = count.as_mut() {
-                                    c.add(cached_count);
-                                } else {
-                                    count = Some(cached_count);
-                                }
-
-                                // Split the query range into before and after the cached range.
-                                let before_fills = server::get_fills_api(
-                                    query_start,
-                                    cached_range.start_timestamp_in_seconds,
-                                )?;
-                                let after_fills = server::get_fills_api(
-                                    cached_range.end_timestamp_in_seconds,
-                                    query_end,
-                                )?;
-
-                                let before_count = self.count_from_range(
-                                    &before_fills,
-                                    (query_start, cached_range.start_timestamp_in_seconds).into(),
-                                );
-                                let after_count = self.count_from_range(
-                                    &after_fills,
-                                    (cached_range.end_timestamp_in_seconds, query_end).into(),
-                                );
-
-                                to_update.push((
-                                    (query_start, cached_range.start_timestamp_in_seconds).into(),
-                                    before_fills,
-                                ));
-                                to_update.push((
-                                    (cached_range.end_timestamp_in_seconds, query_end).into(),
-                                    after_fills,
-                                ));
-
-                                if let Some(c) = count.as_mut() {
-                                    c.add(before_count);
-                                    c.add(after_count);
-                                } else {
-                                    count = Some(before_count);
-                                    count.unwrap().add(after_count);
-                                }
-
-                                // Break out after processing the split ranges.
-                                done = true;
-                                break;
-                            } else if query_start <= cached_range.start_timestamp_in_seconds
-                                && query_end <= cached_range.end_timestamp_in_seconds
-                            {
-                                let cached_count = self.count_from_range(
-                                    fills,
-                                    (cached_range.start_timestamp_in_seconds, query_end).into(),
-                                );
-
-                                if let Some(c) = count.as_mut() {
-                                    c.add(cached_count);
-                                } else {
-                                    count = Some(cached_count);
-                                }
-
-                                // Update query range to exclude the part that is already cached.
-                                query_end = cached_range.start_timestamp_in_seconds;
-                                continue;
-                            } else if query_start >= cached_range.start_timestamp_in_seconds
-                                && query_end >= cached_range.end_timestamp_in_seconds
-                            {
-                                let cached_count = self.count_from_range(
-                                    fills,
-                                    (query_start, cached_range.end_timestamp_in_seconds).into(),
-                                );
-
-                                if let Some(c) = count.as_mut() {
-                                    c.add(cached_count);
-                                } else {
-                                    count = Some(cached_count);
-                                }
-
-                                // Update query range to exclude the part that is already cached.
-                                query_start = cached_range.end_timestamp_in_seconds;
-                                continue;
-                            }
-                        }
-                    }
-                }
-            }
+        let index = fill_index();
+        let count: Count = match self.query_type {
+            QueryType::TradingVolume => index.trading_volume(self.range).into(),
+            QueryType::MarketBuys => index.market_buys(self.range).into(),
+            QueryType::MarketSells => index.market_sells(self.range).into(),
+            QueryType::TakerTrades => index.taker_trades(self.range).into(),
+            QueryType::Candles => unreachable!("candle queries returned above"),
+        };
+
+        metrics.record_query(index.range_len(self.range) as u64);
+        Ok(QueryResult::Count(Some(count)))
+    }
 
-            if !done {
-                let remaining_fills = server::get_fills_api(query_start, query_end)?;
+    /// Legacy scalar path: fetch and scan fills per slot through the interval
+    /// cache, preserving the pre-index behavior behind the `legacy-filter`
+    /// feature.
+    #[cfg(feature = "legacy-filter")]
+    pub fn get_count(
+        &self,
+        cache: &QueryCache,
+        metrics: &Metrics,
+    ) -> anyhow::Result<QueryResult> {
+        if let QueryType::Candles = self.query_type {
+            return Ok(QueryResult::Candles(self.get_candles(cache, metrics)?));
+        }
 
-                let range = (query_start, query_end).into();
+        let mut count: Option<Count> = None;
+        let mut scanned = 0u64;
+
+        for (time_slot, (query_start, query_end)) in self.time_slots_map() {
+            let fills = self.slot_fills(cache, metrics, time_slot, query_start, query_end)?;
+            scanned += fills.len() as u64;
+
+            // Every fill in `(query_start, query_end]` is present exactly once,
+            // so a single count over the merged set cannot double-count a trade
+            // whose fills straddled cached fragments.
+            let slot_count = self.count_from_range(&fills, (query_start, query_end).into());
+            if let Some(c) = count.as_mut() {
+                c.add(slot_count);
+            } else {
+                count = Some(slot_count);
+            }
+        }
 
-                let remaining_count = self.count_from_range(&remaining_fills, range);
+        metrics.record_query(scanned);
+        Ok(QueryResult::Count(count))
+    }
+
+    /// Gather every fill in `(query_start, query_end]` for one slot, serving the
+    /// parts covered by the slot's interval cache and fetching only the
+    /// uncovered gaps via `get_fills_api`. Newly fetched gaps are inserted and
+    /// the slot's intervals are coalesced so it always holds a minimal,
+    /// non-overlapping set.
+    fn slot_fills(
+        &self,
+        cache: &QueryCache,
+        metrics: &Metrics,
+        time_slot: Slot,
+        query_start: i64,
+        query_end: i64,
+    ) -> anyhow::Result<Vec<server::Fill>> {
+        // Phase 1: read the covered fills and compute the gaps, holding the lock
+        // only for the read so `get_fills_api` does not serialize workers.
+        let (mut collected, gaps, covered_intervals) = {
+            let mut cache_lock = cache.0.lock().unwrap();
+            Self::plan_slot(cache_lock.get(&time_slot), query_start, query_end)
+        };
 
-                if let Some(c) = count.as_mut() {
-                    c.add(remaining_count);
-                } else {
-                    count = Some(remaining_count);
+        // Phase 2: fetch the uncovered gaps without holding the lock.
+        let mut fetched: Vec<(i64, i64, Vec<server::Fill>)> = Vec::with_capacity(gaps.len());
+        let mut fetched_rows = 0u64;
+        for (gap_start, gap_end) in gaps {
+            let gap_fills = server::get_fills_api(gap_start, gap_end)?;
+            fetched_rows += gap_fills.len() as u64;
+            collected.extend(gap_fills.iter().copied());
+            fetched.push((gap_start, gap_end, gap_fills));
+        }
+        metrics.record_slot(covered_intervals, fetched_rows, fetched.len() as u64);
+
+        // Phase 3: insert the new intervals and coalesce the slot. Another
+        // worker may have inserted intervals covering part of a fetched gap
+        // while we fetched without the lock, so we re-plan against the current
+        // slot and insert only the still-uncovered sub-ranges. This keeps the
+        // slot's intervals disjoint, so `coalesce` only ever merges genuine
+        // adjacency and never duplicates an overlap region's fills.
+        if !fetched.is_empty() {
+            let mut cache_lock = cache.0.lock().unwrap();
+            let slot_cache = match cache_lock.get_mut(&time_slot) {
+                Some(slot_cache) => slot_cache,
+                None => {
+                    cache_lock.put(time_slot, SlotCache::new());
+                    cache_lock.get_mut(&time_slot).unwrap()
                 }
+            };
+            for (gap_start, gap_end, gap_fills) in fetched {
+                for (sub_start, sub_end) in Self::uncovered(slot_cache, gap_start, gap_end) {
+                    let start = DateTime::from_timestamp(sub_start, 0).unwrap();
+                    let end = DateTime::from_timestamp(sub_end, 0).unwrap();
+                    let pieces: Vec<server::Fill> = gap_fills
+                        .iter()
+                        .copied()
+                        .filter(|fill| fill.time > start && fill.time <= end)
+                        .collect();
+                    slot_cache.insert(sub_start, (sub_end, pieces));
+                }
+            }
+            Self::coalesce(slot_cache);
+        }
 
-                {
-                    let mut cache_lock = cache.0.lock().unwrap();
+        Ok(collected)
+    }
 
-                    if let Some(cache_entry) = cache_lock.get_mut(time_slot) {
-                        cache_entry.insert(range, remaining_fills);
-                    } else {
-                        let mut new_cache_entry = HashMap::new();
-                        new_cache_entry.insert(range, remaining_fills);
-                        cache_lock.put(*time_slot, new_cache_entry);
-                    }
+    /// The sub-ranges of `(query_start, query_end]` not already covered by the
+    /// slot's intervals, in order. Unlike `plan_slot` this does not collect
+    /// fills, so it is cheap to call under the write lock.
+    fn uncovered(slot: &SlotCache, query_start: i64, query_end: i64) -> Vec<(i64, i64)> {
+        let mut gaps: Vec<(i64, i64)> = Vec::new();
+        let mut cursor = query_start;
+        for (&start, (end, _)) in slot.range(..query_end) {
+            let covered_start = start.max(query_start);
+            let covered_end = (*end).min(query_end);
+            if covered_end <= query_start || covered_start >= query_end {
+                continue;
+            }
+            if cursor < covered_start {
+                gaps.push((cursor, covered_start));
+            }
+            cursor = cursor.max(covered_end);
+        }
+        if cursor < query_end {
+            gaps.push((cursor, query_end));
+        }
+        gaps
+    }
+
+    /// Walk the intervals of `slot` intersecting `(query_start, query_end]` in
+    /// order, collecting their fills and recording the uncovered gaps between
+    /// them.
+    fn plan_slot(
+        slot: Option<&SlotCache>,
+        query_start: i64,
+        query_end: i64,
+    ) -> (Vec<server::Fill>, Vec<(i64, i64)>, u64) {
+        let mut collected: Vec<server::Fill> = Vec::new();
+        let mut gaps: Vec<(i64, i64)> = Vec::new();
+        let mut covered_intervals = 0u64;
+        let mut cursor = query_start;
+
+        if let Some(slot) = slot {
+            for (&start, (end, fills)) in slot.range(..query_end) {
+                let covered_start = start.max(query_start);
+                let covered_end = (*end).min(query_end);
+                if covered_end <= query_start || covered_start >= query_end {
+                    continue;
                 }
+                if cursor < covered_start {
+                    gaps.push((cursor, covered_start));
+                }
+                let sub: TimeRange = (covered_start, covered_end).into();
+                collected.extend(fills.iter().copied().filter(|fill| {
+                    fill.time > DateTime::from_timestamp(sub.start_timestamp_in_seconds, 0).unwrap()
+                        && fill.time
+                            <= DateTime::from_timestamp(sub.end_timestamp_in_seconds, 0).unwrap()
+                }));
+                covered_intervals += 1;
+                cursor = cursor.max(covered_end);
             }
+        }
 
-            if !to_update.is_empty() {
-                let mut cache_lock = cache.0.lock().unwrap();
+        if cursor < query_end {
+            gaps.push((cursor, query_end));
+        }
 
-                if let Some(cache_entry) = cache_lock.get_mut(time_slot) {
-                    for (range, fills) in to_update {
-                        cache_entry.insert(range, fills);
-                    }
+        (collected, gaps, covered_intervals)
+    }
+
+    /// Merge adjacent or overlapping intervals in place so the slot holds the
+    /// minimal set. Intervals are disjoint by construction, so only genuine
+    /// adjacency (`start == previous end`) triggers a merge, and concatenating
+    /// their fills keeps every fill exactly once.
+    fn coalesce(slot: &mut SlotCache) {
+        let mut merged: Vec<(i64, (i64, Vec<server::Fill>))> = Vec::new();
+        for (start, (end, fills)) in std::mem::take(slot) {
+            if let Some((_, (prev_end, prev_fills))) = merged.last_mut() {
+                if start <= *prev_end {
+                    *prev_end = (*prev_end).max(end);
+                    prev_fills.extend(fills);
+                    continue;
                 }
             }
+            merged.push((start, (end, fills)));
         }
+        *slot = merged.into_iter().collect();
+    }
 
-        Ok(count)
+    /// Emit one OHLCV bar per `SLOT_SIZE` bucket inside the query range,
+    /// reusing `time_slots_map` for bucketing and the per-slot cache of fills
+    /// so the fetch path is shared with the scalar queries. Buckets are ordered
+    /// by start time; empty buckets are omitted.
+    fn get_candles(&self, cache: &QueryCache, metrics: &Metrics) -> anyhow::Result<Vec<Candle>> {
+        let mut buckets: Vec<(i64, Candle)> = Vec::new();
+        let mut scanned = 0u64;
+
+        for (time_slot, (query_start, query_end)) in self.time_slots_map() {
+            let range: TimeRange = (query_start, query_end).into();
+            let fills = self.slot_fills(cache, metrics, time_slot, query_start, query_end)?;
+            scanned += fills.len() as u64;
+
+            if let Some(candle) = Self::candle_from_range(&fills, range, time_slot) {
+                buckets.push((time_slot, candle));
+            }
+        }
+
+        metrics.record_query(scanned);
+        buckets.sort_by_key(|(bucket, _)| *bucket);
+        Ok(buckets.into_iter().map(|(_, candle)| candle).collect())
     }
 }
 
+#[cfg(feature = "legacy-filter")]
 trait CountFilter {
     fn filter_fills<F>(&self, range: TimeRange, filter_func: F) -> usize
     where
@@ -354,6 +613,146 @@ trait CountFilter {
     fn trading_volume(&self, range: TimeRange) -> f64;
 }
 
+/// The process-wide fill index, built once over the full dataset on first use.
+static FILL_INDEX: OnceLock<FillIndex> = OnceLock::new();
+
+/// Return the process-wide `FillIndex`, building it once from the binary fill
+/// store (falling back to `trades.csv`). All scalar count queries share this
+/// single sorted index, so a cold range count is a pair of binary searches
+/// rather than a rebuild-and-scan.
+fn fill_index() -> &'static FillIndex {
+    FILL_INDEX.get_or_init(|| {
+        let fills = store::load_fills().unwrap_or_else(|e| {
+            error!("Failed to load fills for index: {e:?}");
+            Vec::new()
+        });
+        FillIndex::new(&fills)
+    })
+}
+
+/// A sorted-by-time index over a set of fills with cumulative arrays.
+///
+/// Counting over a `(start, end]` range without an index means a linear scan and
+/// a `HashSet<sequence_number>` dedup on every call. Because the full fill set is
+/// loaded once, we can pay for a single sort + prefix pass and then answer every
+/// range count with a pair of binary searches and a subtraction.
+///
+/// Fills belonging to one trade share a `sequence_number` and are contiguous in
+/// time, so a distinct-trade count reduces to counting the *run-starts* (the
+/// first fill of each trade) inside the range, with a direction-filtered
+/// variant for market buys and sells. Trading volume is a straight cumulative
+/// sum of `price * quantity`.
+struct FillIndex {
+    times: Vec<DateTime<Utc>>,
+    seq: Vec<u64>,
+    direction: Vec<i32>,
+    /// `cum_volume[i]` is the summed `price * quantity` of the first `i` fills.
+    cum_volume: Vec<f64>,
+    /// `prefix_runs[i]` is the number of run-starts among the first `i` fills.
+    prefix_runs: Vec<usize>,
+    prefix_buy_runs: Vec<usize>,
+    prefix_sell_runs: Vec<usize>,
+}
+
+impl FillIndex {
+    fn new(fills: &[server::Fill]) -> Self {
+        let mut sorted: Vec<&server::Fill> = fills.iter().collect();
+        sorted.sort_by_key(|fill| fill.time);
+
+        let n = sorted.len();
+        let mut times = Vec::with_capacity(n);
+        let mut seq = Vec::with_capacity(n);
+        let mut direction = Vec::with_capacity(n);
+        let mut cum_volume = Vec::with_capacity(n + 1);
+        let mut prefix_runs = Vec::with_capacity(n + 1);
+        let mut prefix_buy_runs = Vec::with_capacity(n + 1);
+        let mut prefix_sell_runs = Vec::with_capacity(n + 1);
+
+        cum_volume.push(0.0);
+        prefix_runs.push(0);
+        prefix_buy_runs.push(0);
+        prefix_sell_runs.push(0);
+
+        for (i, fill) in sorted.iter().enumerate() {
+            times.push(fill.time);
+            seq.push(fill.sequence_number);
+            direction.push(fill.direction);
+
+            let volume = (fill.price * fill.quantity).to_f64().unwrap_or(0.0);
+            cum_volume.push(cum_volume[i] + volume);
+
+            let run_start = i == 0 || fill.sequence_number != sorted[i - 1].sequence_number;
+            let runs = usize::from(run_start);
+            prefix_runs.push(prefix_runs[i] + runs);
+            prefix_buy_runs.push(prefix_buy_runs[i] + runs * usize::from(fill.direction == 1));
+            prefix_sell_runs.push(prefix_sell_runs[i] + runs * usize::from(fill.direction == -1));
+        }
+
+        Self {
+            times,
+            seq,
+            direction,
+            cum_volume,
+            prefix_runs,
+            prefix_buy_runs,
+            prefix_sell_runs,
+        }
+    }
+
+    /// The half-open index range `[lo, hi)` covering fills with
+    /// `start < time <= end`, matching the inclusive-upper semantics of the
+    /// linear scan.
+    fn bounds(&self, range: TimeRange) -> (usize, usize) {
+        let start = DateTime::from_timestamp(range.start_timestamp_in_seconds, 0).unwrap();
+        let end = DateTime::from_timestamp(range.end_timestamp_in_seconds, 0).unwrap();
+        let lo = self.times.partition_point(|time| *time <= start);
+        let hi = self.times.partition_point(|time| *time <= end);
+        (lo, hi)
+    }
+
+    /// Number of fills inside `range`, for telemetry.
+    fn range_len(&self, range: TimeRange) -> usize {
+        let (lo, hi) = self.bounds(range);
+        hi - lo
+    }
+
+    /// Count distinct runs in `[lo, hi)` from `prefix`, adding back the run that
+    /// straddles `lo`: a fill at `lo` continuing a trade started before `lo` is
+    /// in range but is not itself a run-start, so it must be counted once more
+    /// when its direction matches.
+    fn distinct(&self, prefix: &[usize], lo: usize, hi: usize, matches: impl Fn(i32) -> bool) -> usize {
+        if lo >= hi {
+            return 0;
+        }
+        let mut count = prefix[hi] - prefix[lo];
+        if lo > 0 && self.seq[lo] == self.seq[lo - 1] && matches(self.direction[lo]) {
+            count += 1;
+        }
+        count
+    }
+
+    fn taker_trades(&self, range: TimeRange) -> usize {
+        let (lo, hi) = self.bounds(range);
+        self.distinct(&self.prefix_runs, lo, hi, |_| true)
+    }
+
+    fn market_buys(&self, range: TimeRange) -> usize {
+        let (lo, hi) = self.bounds(range);
+        self.distinct(&self.prefix_buy_runs, lo, hi, |dir| dir == 1)
+    }
+
+    fn market_sells(&self, range: TimeRange) -> usize {
+        let (lo, hi) = self.bounds(range);
+        self.distinct(&self.prefix_sell_runs, lo, hi, |dir| dir == -1)
+    }
+
+    fn trading_volume(&self, range: TimeRange) -> f64 {
+        let (lo, hi) = self.bounds(range);
+        self.cum_volume[hi] - self.cum_volume[lo]
+    }
+}
+
+#[cfg(feature = "legacy-filter")]
 impl CountFilter for &[server::Fill] {
     fn filter_fills<F>(&self, range: TimeRange, filter_func: F) -> usize
     where
@@ -395,31 +794,63 @@ impl CountFilter for &[server::Fill] {
     }
 }
 
-type CountHandles = Vec<JoinHandle<anyhow::Result<Option<Count>>>>;
+/// A unit of work handed to the worker pool: the input line index (for
+/// deterministic ordering) and the raw query string.
+type Job = (usize, String);
 
-type Cache = LruCache<Slot, HashMap<TimeRange, Vec<server::Fill>>>;
+/// A completed job: the input line index and its result.
+type JobResult = (usize, anyhow::Result<QueryResult>);
+
+/// One slot's cached fills as a sorted, non-overlapping interval list keyed by
+/// start timestamp: `start -> (end, fills)`, with each interval holding the
+/// fills in `(start, end]`. The `BTreeMap` keeps intervals ordered so a query
+/// can walk only the intervals intersecting its range.
+type SlotCache = std::collections::BTreeMap<i64, (i64, Vec<server::Fill>)>;
+
+type Cache = LruCache<Slot, SlotCache>;
 struct QueryCache(Arc<Mutex<Cache>>);
 const CACHE_SIZE: usize = 10_000;
 
 type Slot = i64;
 const SLOT_SIZE: i64 = 4500;
 
+/// Binary sidecar holding the warm LRU contents between process runs.
+const CACHE_SIDECAR_PATH: &str = "./query_cache.bin";
+
+/// Flat, serializable projection of the per-slot cache for the sidecar:
+/// `(slot, [(start, end, fills)])`.
+type CacheSnapshot = Vec<(Slot, Vec<(i64, i64, Vec<server::Fill>)>)>;
+
 pub struct Processor {
-    handles: CountHandles,
+    /// Sender for jobs; dropped on `Drop` to signal the workers to finish.
+    job_tx: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    /// Printer thread that emits results in input order.
+    printer: Option<JoinHandle<()>>,
+    next_index: usize,
     cache: QueryCache,
+    metrics: Arc<Metrics>,
 }
 
 impl Drop for Processor {
     #[instrument(skip(self))]
     fn drop(&mut self) {
-        for handle in self.handles.drain(..) {
-            match handle.join() {
-                Ok(Ok(Some(count))) => println!("{count}"),
-                Ok(Ok(None)) => println!("0"),
-                Ok(Err(e)) => error!("Failed to process query: {e:?}"),
-                Err(e) => error!("Failed to join thread when dropping 'Processor': {e:?}"),
+        // Closing the job channel lets each worker drain and exit; once every
+        // worker drops its results sender the printer sees the channel close
+        // and flushes any buffered out-of-order results in input order.
+        self.job_tx.take();
+        for worker in self.workers.drain(..) {
+            if let Err(e) = worker.join() {
+                error!("Failed to join worker thread: {e:?}");
             }
         }
+        if let Some(printer) = self.printer.take() {
+            if let Err(e) = printer.join() {
+                error!("Failed to join printer thread: {e:?}");
+            }
+        }
+        self.flush_cache_sidecar();
+        self.metrics.report("summary");
     }
 }
 
@@ -432,29 +863,162 @@ impl Default for Processor {
 impl Processor {
     pub fn new() -> Self {
         telemetry();
+
+        // The binary fill store is loaded lazily by `fill_index()` on the first
+        // scalar count and serves every subsequent count, so there is no eager
+        // warm here that would parse the CSV only to discard the result.
+        let mut cache: Cache = LruCache::new(NonZeroUsize::new(CACHE_SIZE).unwrap());
+        Self::load_cache_sidecar(&mut cache);
+        let cache = Arc::new(Mutex::new(cache));
+        let metrics = Arc::new(Metrics::new());
+
+        // A fixed-size pool sized to available parallelism, sharing the job
+        // receiver behind a mutex, bounds resource use instead of spawning one
+        // thread per input line.
+        let (job_tx, job_rx) = mpsc::channel::<Job>();
+        let (result_tx, result_rx) = mpsc::channel::<JobResult>();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+
+        let worker_count = available_parallelism().map(NonZeroUsize::get).unwrap_or(4);
+        let mut workers = Vec::with_capacity(worker_count);
+        for _ in 0..worker_count {
+            let job_rx = Arc::clone(&job_rx);
+            let result_tx = result_tx.clone();
+            let cache = Arc::clone(&cache);
+            let metrics = Arc::clone(&metrics);
+            workers.push(thread::spawn(move || {
+                loop {
+                    let job = {
+                        let rx = job_rx.lock().unwrap();
+                        rx.recv()
+                    };
+                    let Ok((index, query)) = job else { break };
+                    let result = Self::run_query(query, &QueryCache(Arc::clone(&cache)), &metrics);
+                    if result_tx.send((index, result)).is_err() {
+                        break;
+                    }
+                }
+            }));
+        }
+        // Drop our own copy so the printer's channel closes once the workers do.
+        drop(result_tx);
+
+        let printer = thread::spawn(move || Self::print_in_order(result_rx));
+
         Processor {
-            handles: Vec::new(),
-            cache: QueryCache(Arc::new(Mutex::new(LruCache::new(
-                NonZeroUsize::new(CACHE_SIZE).unwrap(),
-            )))),
+            job_tx: Some(job_tx),
+            workers,
+            printer: Some(printer),
+            next_index: 0,
+            cache: QueryCache(cache),
+            metrics,
         }
     }
 
-    pub fn process_query(&mut self, query: String) {
-        let cache = QueryCache(Arc::clone(&self.cache.0));
-
-        let handle = thread::spawn(move || -> anyhow::Result<Option<Count>> {
-            let query = match Query::from_str(&query) {
-                Ok(query) => query,
-                Err(e) => {
-                    error!("Failed to parse query: {e}");
-                    return Ok(None);
+    /// Parse and run one query, mirroring the per-line work formerly done inline
+    /// in the spawned thread.
+    fn run_query(
+        query: String,
+        cache: &QueryCache,
+        metrics: &Metrics,
+    ) -> anyhow::Result<QueryResult> {
+        let query = match Query::from_str(&query) {
+            Ok(query) => query,
+            Err(e) => {
+                error!("Failed to parse query: {e}");
+                return Ok(QueryResult::Count(None));
+            }
+        };
+        query.get_count(cache, metrics)
+    }
+
+    /// Drain completed jobs and print them in input order, buffering results
+    /// that arrive before their predecessors.
+    fn print_in_order(result_rx: Receiver<JobResult>) {
+        let mut next = 0usize;
+        let mut pending: BTreeMap<usize, anyhow::Result<QueryResult>> = BTreeMap::new();
+        for (index, result) in result_rx {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&next) {
+                match result {
+                    Ok(result) => println!("{result}"),
+                    Err(e) => error!("Failed to process query: {e:?}"),
                 }
+                next += 1;
+            }
+        }
+        // Results still buffered behind a job that never reported are emitted
+        // in input order once the channel closes.
+        for (_, result) in pending {
+            match result {
+                Ok(result) => println!("{result}"),
+                Err(e) => error!("Failed to process query: {e:?}"),
+            }
+        }
+    }
+
+    /// Repopulate the LRU from the binary sidecar, restoring ranges warmed by a
+    /// previous run. The sidecar is rejected unless it matches the current
+    /// `trades.csv`, so a changed CSV never restores stale fills. The snapshot
+    /// is stored most recent first, so it is replayed in reverse (oldest first)
+    /// to preserve recency.
+    ///
+    /// Note: in the default (index) build only candle (`K`) queries populate the
+    /// interval cache, so the warm sidecar benefits `K` queries only. Scalar
+    /// `C`/`B`/`S`/`V` queries are answered from the shared `FillIndex` and never
+    /// touch this cache; the legacy-filter build routes them through it too.
+    fn load_cache_sidecar(cache: &mut Cache) {
+        let Ok(stamp) = store::csv_stamp() else {
+            return;
+        };
+        let snapshot: CacheSnapshot =
+            match store::read_versioned(CACHE_SIDECAR_PATH, store::CACHE_MAGIC, &stamp) {
+                Ok(snapshot) => snapshot,
+                Err(_) => return,
             };
-            let count = query.get_count(&cache)?;
-            Ok(count)
-        });
-        self.handles.push(handle);
+        for (slot, ranges) in snapshot.into_iter().rev() {
+            let slot_cache: SlotCache = ranges
+                .into_iter()
+                .map(|(start, end, fills)| (start, (end, fills)))
+                .collect();
+            cache.put(slot, slot_cache);
+        }
+    }
+
+    /// Flush the warm LRU contents to the binary sidecar so a restarted process
+    /// keeps its cached ranges.
+    fn flush_cache_sidecar(&self) {
+        let snapshot: CacheSnapshot = {
+            let cache_lock = self.cache.0.lock().unwrap();
+            cache_lock
+                .iter()
+                .map(|(slot, ranges)| {
+                    let intervals = ranges
+                        .iter()
+                        .map(|(start, (end, fills))| (*start, *end, fills.clone()))
+                        .collect();
+                    (*slot, intervals)
+                })
+                .collect()
+        };
+        let Ok(stamp) = store::csv_stamp() else {
+            return;
+        };
+        if let Err(e) =
+            store::write_versioned(CACHE_SIDECAR_PATH, store::CACHE_MAGIC, &stamp, &snapshot)
+        {
+            error!("Failed to flush query cache sidecar: {e:?}");
+        }
+    }
+
+    pub fn process_query(&mut self, query: String) {
+        let index = self.next_index;
+        self.next_index += 1;
+        if let Some(job_tx) = self.job_tx.as_ref() {
+            if let Err(e) = job_tx.send((index, query)) {
+                error!("Failed to enqueue query: {e}");
+            }
+        }
     }
 }
 
@@ -0,0 +1,124 @@
+//! Binary, memory-mapped persistence for fills and warm query caches.
+//!
+//! Parsing `trades.csv` with `csv::Reader` on every process start is slow and
+//! repeated work. This module serializes `Vec<Fill>` to a compact binary blob
+//! once, then `mmap`s it so `load_fills` skips the CSV parse on subsequent
+//! starts, matching the binary-serialization + memmap approach used by the
+//! external data-pipeline tooling. `server::get_fills_api` still reads
+//! `trades.csv` itself, so a process that fetches through it parses the CSV
+//! once there. The same versioned container backs a persistent
+//! query cache sidecar, so a restarted process keeps its warm ranges.
+//!
+//! Each file begins with an 8-byte magic/version tag followed by a [`DataStamp`]
+//! recording the source `trades.csv`'s length and mtime. A blob is rejected —
+//! and callers fall back to re-reading the CSV — when the schema tag does not
+//! match *or* when the stamp differs from the current CSV, so a changed CSV can
+//! never be served from a stale blob.
+
+use std::{fs, fs::File, io::Write, path::Path, time::UNIX_EPOCH};
+
+use anyhow::{bail, Context};
+use memmap2::Mmap;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::server::Fill;
+
+const CSV_PATH: &str = "./trades.csv";
+const FILL_STORE_PATH: &str = "./trades.bin";
+
+/// Bump the trailing digits whenever the on-disk layout of a blob changes so
+/// stale files are rejected rather than silently mis-read.
+const FILL_MAGIC: &[u8; 8] = b"FILLBIN1";
+pub const CACHE_MAGIC: &[u8; 8] = b"QCACHE01";
+
+/// Identity of the source `trades.csv` at the time a blob was written. A blob
+/// whose stamp differs from the current CSV is treated as stale.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataStamp {
+    len: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+}
+
+/// The stamp of the current `trades.csv`, or an error if its metadata or mtime
+/// is unavailable (in which case callers should skip the binary blobs).
+pub fn csv_stamp() -> anyhow::Result<DataStamp> {
+    let metadata = fs::metadata(CSV_PATH).context("Failed to stat trades.csv")?;
+    let mtime = metadata
+        .modified()
+        .context("trades.csv mtime unavailable")?
+        .duration_since(UNIX_EPOCH)
+        .context("trades.csv mtime precedes the epoch")?;
+    Ok(DataStamp {
+        len: metadata.len(),
+        mtime_secs: mtime.as_secs(),
+        mtime_nanos: mtime.subsec_nanos(),
+    })
+}
+
+/// Load fills from the memory-mapped binary store, falling back to a CSV parse
+/// (and rebuilding the binary store) when the blob is missing, schema-stale, or
+/// no longer matches the current CSV.
+pub fn load_fills() -> anyhow::Result<Vec<Fill>> {
+    // Without a stamp we cannot detect a changed CSV, so skip the blob entirely
+    // and parse the CSV directly.
+    let Ok(stamp) = csv_stamp() else {
+        return load_fills_csv();
+    };
+
+    match read_versioned::<Vec<Fill>>(FILL_STORE_PATH, FILL_MAGIC, &stamp) {
+        Ok(fills) => Ok(fills),
+        Err(_) => {
+            let fills = load_fills_csv()?;
+            // Best-effort: a read-only filesystem should not fail the load.
+            let _ = write_versioned(FILL_STORE_PATH, FILL_MAGIC, &stamp, &fills);
+            Ok(fills)
+        }
+    }
+}
+
+fn load_fills_csv() -> anyhow::Result<Vec<Fill>> {
+    let mut rdr = csv::Reader::from_path(CSV_PATH).context("Failed to find trades.csv")?;
+    Ok(rdr
+        .deserialize()
+        .filter_map(|result| result.ok())
+        .collect())
+}
+
+/// Deserialize a `bincode` value from a memory-mapped file, checking the leading
+/// magic/version tag and rejecting the blob if its stamp differs from `expected`.
+pub fn read_versioned<T: DeserializeOwned>(
+    path: &str,
+    magic: &[u8; 8],
+    expected: &DataStamp,
+) -> anyhow::Result<T> {
+    if !Path::new(path).exists() {
+        bail!("no store at {path}");
+    }
+    let file = File::open(path).with_context(|| format!("Failed to open {path}"))?;
+    let mmap = unsafe { Mmap::map(&file)? };
+    if mmap.len() < magic.len() || &mmap[..magic.len()] != magic {
+        bail!("stale or unrecognized header in {path}");
+    }
+    let (stamp, value): (DataStamp, T) = bincode::deserialize(&mmap[magic.len()..])
+        .with_context(|| format!("Failed to deserialize {path}"))?;
+    if stamp != *expected {
+        bail!("{path} does not match the current trades.csv");
+    }
+    Ok(value)
+}
+
+/// Serialize a value to `path` as the magic/version tag followed by the
+/// `bincode` encoding of `(stamp, value)`.
+pub fn write_versioned<T: Serialize>(
+    path: &str,
+    magic: &[u8; 8],
+    stamp: &DataStamp,
+    value: &T,
+) -> anyhow::Result<()> {
+    let bytes = bincode::serialize(&(stamp, value)).context("Failed to serialize store")?;
+    let mut file = File::create(path).with_context(|| format!("Failed to create {path}"))?;
+    file.write_all(magic)?;
+    file.write_all(&bytes)?;
+    Ok(())
+}